@@ -1,37 +1,591 @@
+// Every `extern "C"` entry point in this file that takes a handle takes it as a
+// raw pointer and null-checks it itself before dereferencing, which is the
+// actual safety contract for a C ABI; `clippy::not_unsafe_ptr_arg_deref`
+// wants the functions marked `unsafe fn` on top of that; doing so would only
+// make every call site in this crate's own tests `unsafe` too, since the
+// danger is "the caller passed a bad pointer", not "calling this at all is
+// unsafe in the Rust sense" as long as `handle` really points to
+// what the API says it does.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::ffi::{CStr, CString};
-use std::os::raw::c_char;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{mpsc, Arc};
+use std::hash::{Hash, Hasher};
+use std::os::raw::{c_char, c_int};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
 use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Callback invoked for each mix item. Returns `0` on success, non-zero on a
+/// transient failure that should be retried (see `RetryConfig`).
+type MixCallback = Option<unsafe extern "C" fn(*const c_char, *const c_char) -> c_int>;
+
+/// Callback invoked when an item's watchdog (see `TimeoutConfig`) abandons it
+/// before the real callback returned.
+type TimeoutCallback = Option<unsafe extern "C" fn(*const c_char, *const c_char)>;
+
+/// Listener invoked for lifecycle events (see `EventKind`) with
+/// `(event_kind, playlist_id, item_id)`.
+type EventListener = unsafe extern "C" fn(c_int, *const c_char, *const c_char);
+
+/// Lifecycle transitions reported to listeners registered via
+/// `sara_mix_executor_subscribe`.
+#[derive(Clone, Copy)]
+#[repr(i32)]
+enum EventKind {
+    Enqueued = 0,
+    Started = 1,
+    Completed = 2,
+    Failed = 3,
+    Cancelled = 4,
+}
+
+/// Status codes returned by `sara_mix_executor_enqueue`.
+const ENQUEUE_OK: c_int = 0;
+const ENQUEUE_FULL: c_int = 1;
+const ENQUEUE_DEAD: c_int = 2;
+/// Too many watchdog-abandoned callbacks are still running; see `TimeoutConfig`.
+const ENQUEUE_BUSY: c_int = 3;
 
-type MixCallback = Option<unsafe extern "C" fn(*const c_char, *const c_char)>;
+/// How long a single wait tick between backoff-sleep checks may be; keeps
+/// `sara_mix_executor_destroy` from blocking for a full backoff interval.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(10);
 
 enum Message {
-    Work(String, String),
+    Work(String, String, u64),
     Shutdown,
 }
 
+/// A pending-item key used by coalescing mode to detect duplicate work
+/// that is already queued but not yet started.
+type PendingKey = (String, String);
+type PendingSet = Arc<Mutex<HashSet<PendingKey>>>;
+
+/// Tracks in-flight callback attempts so `sara_mix_executor_cancel` only
+/// affects attempts that are actually running at the time of the call, never
+/// a later, unrelated item for the same playlist. Each dequeued item gets a
+/// fresh, never-reused token from `begin`; `cancel_playlist` marks only the
+/// tokens live for that playlist at that instant, so a stale mark can never
+/// match a future item's (different) token.
+struct Cancellation {
+    next_token: AtomicU64,
+    in_flight: Mutex<HashMap<String, HashSet<u64>>>,
+    cancelled: Mutex<HashSet<u64>>,
+}
+
+impl Cancellation {
+    fn new() -> Arc<Cancellation> {
+        Arc::new(Cancellation {
+            next_token: AtomicU64::new(1),
+            in_flight: Mutex::new(HashMap::new()),
+            cancelled: Mutex::new(HashSet::new()),
+        })
+    }
+
+    /// Registers a new in-flight attempt for `playlist_id`, returning its token.
+    fn begin(&self, playlist_id: &str) -> u64 {
+        let token = self.next_token.fetch_add(1, Ordering::Relaxed);
+        self.in_flight
+            .lock()
+            .unwrap()
+            .entry(playlist_id.to_string())
+            .or_default()
+            .insert(token);
+        token
+    }
+
+    /// Marks `token`'s attempt as finished, whatever the outcome, and drops
+    /// any leftover cancellation mark for it.
+    fn finish(&self, playlist_id: &str, token: u64) {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if let Some(tokens) = in_flight.get_mut(playlist_id) {
+            tokens.remove(&token);
+            if tokens.is_empty() {
+                in_flight.remove(playlist_id);
+            }
+        }
+        drop(in_flight);
+        self.cancelled.lock().unwrap().remove(&token);
+    }
+
+    /// Returns whether `token` was cancelled since it began, consuming the mark.
+    fn is_cancelled(&self, token: u64) -> bool {
+        self.cancelled.lock().unwrap().remove(&token)
+    }
+
+    /// Cancels every attempt currently in flight for `playlist_id`. Attempts
+    /// started after this call are unaffected.
+    fn cancel_playlist(&self, playlist_id: &str) {
+        if let Some(tokens) = self.in_flight.lock().unwrap().get(playlist_id) {
+            self.cancelled.lock().unwrap().extend(tokens.iter().copied());
+        }
+    }
+}
+
+/// Orders the `Enqueued` and `Started` events of a single item across the two
+/// threads that emit them. `sara_mix_executor_enqueue` pushes an item and
+/// then, outside any lock shared with the workers (see `Listeners::emit`'s
+/// doc comment for why), fires `Enqueued`; a worker can otherwise pop that
+/// same item and fire `Started` first. Each item gets a fresh sequence
+/// number at enqueue time; a worker waits for that number to be marked
+/// announced before firing `Started`, so a listener always observes
+/// `Enqueued` before `Started` for the same item.
+///
+/// A cancelled item is removed from the queue and never popped by a worker,
+/// so nothing would ever call `wait_for` to clear its entry; `forget` lets
+/// `sara_mix_executor_cancel` reclaim it instead, whichever of `mark_announced`
+/// / `forget` loses the race to run first for that sequence number.
+///
+/// This ordering only matters to a subscribed listener, so callers on the
+/// enqueue/worker/cancel side skip the sequence number and every method here
+/// entirely (see `NO_BARRIER_SEQ`) whenever `Listeners::has_subscribers`
+/// reads false, rather than pay this lock+condvar round-trip on every
+/// enqueue and dequeue of every executor, including ones no one is
+/// listening to and every shard of a `sara_mix_executor_create_pool` pool.
+struct EnqueueBarrier {
+    next_seq: AtomicU64,
+    state: Mutex<EnqueueBarrierState>,
+    announced_cv: Condvar,
+}
+
+#[derive(Default)]
+struct EnqueueBarrierState {
+    announced: HashSet<u64>,
+    forgotten: HashSet<u64>,
+}
+
+/// Sentinel `Message::Work` sequence number meaning "no listener was
+/// subscribed at enqueue time, so skip the barrier entirely"; real sequence
+/// numbers from `EnqueueBarrier::next_seq` start at 1 and never collide with it.
+const NO_BARRIER_SEQ: u64 = 0;
+
+impl EnqueueBarrier {
+    fn new() -> Arc<EnqueueBarrier> {
+        Arc::new(EnqueueBarrier {
+            next_seq: AtomicU64::new(1),
+            state: Mutex::new(EnqueueBarrierState::default()),
+            announced_cv: Condvar::new(),
+        })
+    }
+
+    /// Reserves the next sequence number for an item about to be pushed.
+    fn next_seq(&self) -> u64 {
+        self.next_seq.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Marks `seq`'s `Enqueued` event as having fired. A no-op, beyond
+    /// clearing the tombstone, if `forget` already claimed this `seq`.
+    fn mark_announced(&self, seq: u64) {
+        let mut state = self.state.lock().unwrap();
+        if !state.forgotten.remove(&seq) {
+            state.announced.insert(seq);
+            self.announced_cv.notify_all();
+        }
+    }
+
+    /// Blocks until `seq` has been marked announced, then clears the mark.
+    fn wait_for(&self, seq: u64) {
+        let mut state = self.state.lock().unwrap();
+        while !state.announced.remove(&seq) {
+            state = self.announced_cv.wait(state).unwrap();
+        }
+    }
+
+    /// Reclaims `seq` for an item that was cancelled before a worker ever
+    /// popped it, so its entry doesn't linger forever.
+    fn forget(&self, seq: u64) {
+        let mut state = self.state.lock().unwrap();
+        if !state.announced.remove(&seq) {
+            state.forgotten.insert(seq);
+        }
+    }
+}
+
+/// Registered lifecycle listeners, keyed by an opaque subscription id so
+/// `sara_mix_executor_unsubscribe` can remove one without disturbing the
+/// others.
+struct Listeners {
+    next_id: AtomicU64,
+    /// Mirrors `entries.len()` so `has_subscribers` (checked on every
+    /// enqueue/dequeue to decide whether to pay for `EnqueueBarrier`) doesn't
+    /// need to take `entries`'s lock just to find out it's empty.
+    count: AtomicUsize,
+    entries: Mutex<Vec<(u64, EventListener)>>,
+}
+
+impl Listeners {
+    fn new() -> Arc<Listeners> {
+        Arc::new(Listeners {
+            next_id: AtomicU64::new(1),
+            count: AtomicUsize::new(0),
+            entries: Mutex::new(Vec::new()),
+        })
+    }
+
+    fn subscribe(&self, listener: EventListener) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.entries.lock().unwrap().push((id, listener));
+        self.count.fetch_add(1, Ordering::Relaxed);
+        id
+    }
+
+    fn unsubscribe(&self, subscription_id: u64) {
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.len();
+        entries.retain(|(id, _)| *id != subscription_id);
+        if entries.len() != before {
+            self.count.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether any listener is currently subscribed, without taking
+    /// `entries`'s lock. Racy against a concurrent subscribe/unsubscribe by
+    /// design: callers only use this to decide whether paying for
+    /// `EnqueueBarrier`'s ordering guarantee is worthwhile for a given item,
+    /// not to decide whether `emit` has anything to call.
+    fn has_subscribers(&self) -> bool {
+        self.count.load(Ordering::Relaxed) > 0
+    }
+
+    fn emit(&self, kind: EventKind, playlist_id: &str, item_id: &str) {
+        // Clone the (small, Copy) entries and drop the lock before calling
+        // out to arbitrary C callbacks: a listener that calls subscribe/
+        // unsubscribe back into this handle would otherwise deadlock on the
+        // non-reentrant mutex, and holding it for the whole fan-out would
+        // serialize every pool worker's event emission on one global lock.
+        let entries: Vec<(u64, EventListener)> = {
+            let guard = self.entries.lock().unwrap();
+            if guard.is_empty() {
+                return;
+            }
+            guard.clone()
+        };
+        let playlist = CString::new(playlist_id).unwrap_or_else(|_| CString::new("").unwrap());
+        let item = CString::new(item_id).unwrap_or_else(|_| CString::new("").unwrap());
+        for (_, listener) in &entries {
+            unsafe { listener(kind as c_int, playlist.as_ptr(), item.as_ptr()) };
+        }
+    }
+}
+
+struct QueueState {
+    items: VecDeque<Message>,
+}
+
+/// The shared work queue. `capacity` is `None` for an unbounded queue and
+/// `Some(n)` for a bounded one; `sara_mix_executor_enqueue` reports
+/// `ENQUEUE_FULL` when a bounded queue is at capacity rather than growing
+/// without bound.
+struct Queue {
+    state: Mutex<QueueState>,
+    not_empty: Condvar,
+    capacity: Option<usize>,
+}
+
+impl Queue {
+    fn new(capacity: Option<usize>) -> Arc<Queue> {
+        Arc::new(Queue {
+            state: Mutex::new(QueueState { items: VecDeque::new() }),
+            not_empty: Condvar::new(),
+            capacity,
+        })
+    }
+
+    /// Enqueues `msg`, respecting `capacity`. Never blocks.
+    fn try_push(&self, msg: Message) -> Result<(), c_int> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(capacity) = self.capacity {
+            if state.items.len() >= capacity {
+                return Err(ENQUEUE_FULL);
+            }
+        }
+        state.items.push_back(msg);
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Enqueues `msg` regardless of capacity; used for the `Shutdown`
+    /// sentinel, which must never be dropped for being "full".
+    fn push_always(&self, msg: Message) {
+        let mut state = self.state.lock().unwrap();
+        state.items.push_back(msg);
+        self.not_empty.notify_one();
+    }
+
+    /// Blocks until an item is available, then pops and returns it.
+    fn pop_blocking(&self) -> Message {
+        let mut state = self.state.lock().unwrap();
+        while state.items.is_empty() {
+            state = self.not_empty.wait(state).unwrap();
+        }
+        state.items.pop_front().unwrap()
+    }
+
+    /// Removes all not-yet-started `Work` items for `playlist_id`, returning
+    /// the `(playlist_id, item_id, seq)` triples that were removed.
+    fn cancel_playlist(&self, playlist_id: &str) -> Vec<(String, String, u64)> {
+        let mut state = self.state.lock().unwrap();
+        let mut removed = Vec::new();
+        state.items.retain(|msg| match msg {
+            Message::Work(pl, it, seq) if pl == playlist_id => {
+                removed.push((pl.clone(), it.clone(), *seq));
+                false
+            }
+            _ => true,
+        });
+        removed
+    }
+}
+
+/// Retry behavior applied to a callback that reports a transient failure.
+#[derive(Clone, Copy)]
+struct RetryConfig {
+    max_retries: u32,
+    base_delay: Duration,
+    ceiling: Duration,
+}
+
+impl RetryConfig {
+    /// No retries: a failed callback is attempted exactly once.
+    const NONE: RetryConfig = RetryConfig {
+        max_retries: 0,
+        base_delay: Duration::from_millis(0),
+        ceiling: Duration::from_millis(0),
+    };
+
+    /// Delay before retry attempt `attempt` (1-based), before jitter.
+    fn delay_for(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt - 1).unwrap_or(u32::MAX);
+        self.base_delay.saturating_mul(factor).min(self.ceiling)
+    }
+}
+
+/// Per-item watchdog configuration. The C callback is opaque and can't be
+/// force-killed safely, so a timed-out item is handled as "abandon and
+/// continue": the worker stops waiting on it and moves on to the next queued
+/// item, while the orphaned callback thread is left to finish on its own.
+/// Once the number of such orphans still running reaches `max_abandoned`,
+/// `sara_mix_executor_enqueue` returns `ENQUEUE_BUSY`. Items already queued
+/// are still run and can be abandoned too, so the number of orphans can go
+/// past `max_abandoned`.
+#[derive(Clone)]
+struct TimeoutConfig {
+    duration: Duration,
+    max_abandoned: usize,
+    on_timeout: TimeoutCallback,
+    abandoned: Arc<AtomicUsize>,
+}
+
+/// Small xorshift PRNG, good enough for retry jitter (no cryptographic use).
+struct Rng(u64);
+
+impl Rng {
+    fn new() -> Rng {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0x9e3779b97f4a7c15);
+        let thread_salt = {
+            let mut hasher = DefaultHasher::new();
+            thread::current().id().hash(&mut hasher);
+            hasher.finish()
+        };
+        Rng((nanos ^ thread_salt) | 1)
+    }
+
+    /// Returns a jitter multiplier in `[0.75, 1.25)`.
+    fn jitter(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        let unit = (self.0 >> 11) as f64 / (1u64 << 53) as f64;
+        0.75 + unit * 0.5
+    }
+}
+
+/// Sleeps for `duration`, polling `alive` every `SHUTDOWN_POLL_INTERVAL` so a
+/// shutdown request interrupts the wait instead of blocking it out. Returns
+/// `true` if the sleep was cut short by a shutdown.
+fn sleep_responsive(alive: &AtomicBool, duration: Duration) -> bool {
+    let mut remaining = duration;
+    while remaining > Duration::ZERO {
+        if !alive.load(Ordering::Relaxed) {
+            return true;
+        }
+        let step = remaining.min(SHUTDOWN_POLL_INTERVAL);
+        thread::sleep(step);
+        remaining = remaining.saturating_sub(step);
+    }
+    !alive.load(Ordering::Relaxed)
+}
+
+/// Hashes a playlist id for the per-playlist serialization mode of
+/// `sara_mix_executor_create_pool`, pinning every item of a playlist to the
+/// same worker queue/thread.
+fn hash_str(s: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    s.hash(&mut hasher);
+    hasher.finish()
+}
+
 #[repr(C)]
 pub struct SaraMixExecutor {
     alive: Arc<AtomicBool>,
-    tx: mpsc::Sender<Message>,
-    thread: Option<thread::JoinHandle<()>>,
+    /// One queue per worker thread. In the default pool mode every entry is
+    /// a clone of the same `Arc<Queue>` (all workers share one queue); in
+    /// per-playlist serialization mode each entry is its own distinct queue.
+    worker_queues: Vec<Arc<Queue>>,
+    /// Whether work is routed to `worker_queues[hash(playlist_id) % n]`
+    /// (serialized per playlist) rather than to the single shared queue.
+    sharded: bool,
+    threads: Vec<thread::JoinHandle<()>>,
+    ctx: Arc<WorkerContext>,
 }
 
-fn worker(rx: mpsc::Receiver<Message>, alive: Arc<AtomicBool>, callback: MixCallback) {
-    while alive.load(Ordering::Relaxed) {
-        let msg = match rx.recv() {
-            Ok(value) => value,
-            Err(_) => break,
-        };
+impl SaraMixExecutor {
+    /// The queue new work for `playlist_id` should be pushed into / cancelled from.
+    fn route_queue(&self, playlist_id: &str) -> &Arc<Queue> {
+        if self.sharded {
+            let idx = (hash_str(playlist_id) as usize) % self.worker_queues.len();
+            &self.worker_queues[idx]
+        } else {
+            &self.worker_queues[0]
+        }
+    }
+}
+
+/// The state shared by every worker thread of an executor (and by the
+/// executor itself, for enqueue/cancel/subscribe), bundled into one struct
+/// so `worker` takes a queue, an alive flag, and this instead of a long run
+/// of loose arguments.
+struct WorkerContext {
+    pending: Option<PendingSet>,
+    cancellation: Arc<Cancellation>,
+    listeners: Arc<Listeners>,
+    enqueue_barrier: Arc<EnqueueBarrier>,
+    callback: MixCallback,
+    retry: RetryConfig,
+    timeout: Option<TimeoutConfig>,
+}
+
+/// Runs `cb(playlist, item)`, optionally under a watchdog. Returns `Some(status)`
+/// if the callback completed in time, or `None` if `timeout` abandoned it (the
+/// callback keeps running on its own orphaned thread) or `alive` went false.
+///
+/// The wait is polled in `SHUTDOWN_POLL_INTERVAL` ticks rather than one
+/// `recv_timeout(timeout.duration)` call so `sara_mix_executor_destroy` never
+/// blocks for a full watchdog timeout, matching `sleep_responsive`'s
+/// responsiveness guarantee for the backoff wait.
+fn call_with_watchdog(
+    cb: unsafe extern "C" fn(*const c_char, *const c_char) -> c_int,
+    playlist: &CString,
+    item: &CString,
+    timeout: &Option<TimeoutConfig>,
+    alive: &AtomicBool,
+) -> Option<c_int> {
+    let Some(timeout) = timeout else {
+        return Some(unsafe { cb(playlist.as_ptr(), item.as_ptr()) });
+    };
+    let (tx, rx) = mpsc::channel();
+    let playlist_owned = playlist.clone();
+    let item_owned = item.clone();
+    thread::spawn(move || {
+        let status = unsafe { cb(playlist_owned.as_ptr(), item_owned.as_ptr()) };
+        let _ = tx.send(status);
+    });
+
+    let deadline = Instant::now() + timeout.duration;
+    loop {
+        if !alive.load(Ordering::Relaxed) {
+            return None;
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        match rx.recv_timeout(remaining.min(SHUTDOWN_POLL_INTERVAL)) {
+            Ok(status) => return Some(status),
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => return None,
+        }
+    }
+
+    timeout.abandoned.fetch_add(1, Ordering::Relaxed);
+    if let Some(on_timeout) = timeout.on_timeout {
+        unsafe { on_timeout(playlist.as_ptr(), item.as_ptr()) };
+    }
+    // The orphaned thread above is still running `cb`; once it
+    // finishes (however late) it frees its reserved slot.
+    let abandoned = Arc::clone(&timeout.abandoned);
+    thread::spawn(move || {
+        let _ = rx.recv();
+        abandoned.fetch_sub(1, Ordering::Relaxed);
+    });
+    None
+}
+
+fn worker(queue: Arc<Queue>, alive: Arc<AtomicBool>, ctx: Arc<WorkerContext>) {
+    let mut rng = Rng::new();
+    'outer: while alive.load(Ordering::Relaxed) {
+        let msg = queue.pop_blocking();
         match msg {
-            Message::Work(playlist_id, item_id) => {
-                if let Some(cb) = callback {
-                    let playlist = CString::new(playlist_id).unwrap_or_else(|_| CString::new("").unwrap());
-                    let item = CString::new(item_id).unwrap_or_else(|_| CString::new("").unwrap());
-                    unsafe {
-                        cb(playlist.as_ptr(), item.as_ptr());
+            Message::Work(playlist_id, item_id, seq) => {
+                if let Some(pending) = &ctx.pending {
+                    pending.lock().unwrap().remove(&(playlist_id.clone(), item_id.clone()));
+                }
+                // Clears this item's enqueue_barrier entry (even with no
+                // callback configured) so it's never left behind; also makes
+                // sure this item's `Enqueued` event has already fired before
+                // firing `Started`, even though the two come from different
+                // threads with no lock held across either call. `seq` is
+                // `NO_BARRIER_SEQ` when no listener was subscribed at enqueue
+                // time, so there's no ordering to guarantee and nothing to
+                // clear.
+                if seq != NO_BARRIER_SEQ {
+                    ctx.enqueue_barrier.wait_for(seq);
+                }
+                let Some(cb) = ctx.callback else { continue };
+                ctx.listeners.emit(EventKind::Started, &playlist_id, &item_id);
+                let playlist = CString::new(playlist_id.clone()).unwrap_or_else(|_| CString::new("").unwrap());
+                let item = CString::new(item_id.clone()).unwrap_or_else(|_| CString::new("").unwrap());
+                let token = ctx.cancellation.begin(&playlist_id);
+
+                let mut attempt = 0;
+                let outcome;
+                loop {
+                    // Only a retry (not the first attempt) can be skipped by a
+                    // cancellation, so a cancellation mark can never suppress
+                    // a fresh item's first attempt.
+                    if attempt > 0 && ctx.cancellation.is_cancelled(token) {
+                        outcome = Some(EventKind::Cancelled);
+                        break;
+                    }
+                    let Some(status) = call_with_watchdog(cb, &playlist, &item, &ctx.timeout, &alive) else {
+                        // Timed out: abandon this item entirely and move on,
+                        // rather than retrying it.
+                        outcome = Some(EventKind::Failed);
+                        break;
+                    };
+                    if status == 0 {
+                        outcome = Some(EventKind::Completed);
+                        break;
+                    }
+                    if attempt >= ctx.retry.max_retries {
+                        outcome = Some(EventKind::Failed);
+                        break;
                     }
+                    attempt += 1;
+                    let delay = ctx.retry.delay_for(attempt).mul_f64(rng.jitter());
+                    if sleep_responsive(&alive, delay) {
+                        ctx.cancellation.finish(&playlist_id, token);
+                        break 'outer;
+                    }
+                }
+                ctx.cancellation.finish(&playlist_id, token);
+                if let Some(outcome) = outcome {
+                    ctx.listeners.emit(outcome, &playlist_id, &item_id);
                 }
             }
             Message::Shutdown => break,
@@ -39,31 +593,307 @@ fn worker(rx: mpsc::Receiver<Message>, alive: Arc<AtomicBool>, callback: MixCall
     }
 }
 
-#[no_mangle]
-pub extern "C" fn sara_mix_executor_create(callback: MixCallback) -> *mut SaraMixExecutor {
-    let (tx, rx) = mpsc::channel::<Message>();
+/// Spawns one worker thread per entry in `worker_queues` and bundles them
+/// into an executor. `sharded` records whether those queues are distinct
+/// (per-playlist serialization) or clones of a single shared queue (a plain
+/// worker pool).
+fn build_executor(worker_queues: Vec<Arc<Queue>>, sharded: bool, ctx: WorkerContext) -> *mut SaraMixExecutor {
     let alive = Arc::new(AtomicBool::new(true));
-    let alive_thread = Arc::clone(&alive);
-    let thread = thread::spawn(move || worker(rx, alive_thread, callback));
+    let ctx = Arc::new(ctx);
+    let threads = worker_queues
+        .iter()
+        .map(|queue| {
+            let queue = Arc::clone(queue);
+            let alive = Arc::clone(&alive);
+            let ctx = Arc::clone(&ctx);
+            thread::spawn(move || worker(queue, alive, ctx))
+        })
+        .collect();
     Box::into_raw(Box::new(SaraMixExecutor {
         alive,
-        tx,
-        thread: Some(thread),
+        worker_queues,
+        sharded,
+        threads,
+        ctx,
     }))
 }
 
+/// The watchdog half of a builder's configuration. `TimeoutConfig` itself
+/// carries a live `abandoned` counter that only makes sense once an executor
+/// is actually built, so the builder holds this plain spec instead.
+struct TimeoutSpec {
+    timeout_ms: u64,
+    max_abandoned: usize,
+    on_timeout: TimeoutCallback,
+}
+
+/// Collects the options behind `sara_mix_executor_create_bounded`,
+/// `_create_ex`, `_create_with_timeout` and `_create_pool` into one config so
+/// those constructors share a single build path instead of duplicating
+/// `build_executor`/`WorkerContext` setup. Internal only: there is no public
+/// C entry point for combining options, since nothing in the backlog calls
+/// for one.
+struct SaraMixExecutorBuilder {
+    capacity: Option<usize>,
+    coalesce: bool,
+    retry: RetryConfig,
+    timeout: Option<TimeoutSpec>,
+    threads: usize,
+    serialize_per_playlist: bool,
+}
+
+impl SaraMixExecutorBuilder {
+    fn new() -> SaraMixExecutorBuilder {
+        SaraMixExecutorBuilder {
+            capacity: None,
+            coalesce: false,
+            retry: RetryConfig::NONE,
+            timeout: None,
+            threads: 1,
+            serialize_per_playlist: false,
+        }
+    }
+
+    fn build(self, callback: MixCallback) -> *mut SaraMixExecutor {
+        // `route_queue` indexes/mods by `worker_queues.len()`, so a 0-thread
+        // pool would panic on the first enqueue/cancel; clamp to a single
+        // worker.
+        let threads = self.threads.max(1);
+        let worker_queues: Vec<Arc<Queue>> = if threads == 1 {
+            vec![Queue::new(self.capacity)]
+        } else if self.serialize_per_playlist {
+            (0..threads).map(|_| Queue::new(self.capacity)).collect()
+        } else {
+            let shared = Queue::new(self.capacity);
+            (0..threads).map(|_| Arc::clone(&shared)).collect()
+        };
+        let sharded = threads > 1 && self.serialize_per_playlist;
+        let pending = if self.coalesce { Some(Arc::new(Mutex::new(HashSet::new()))) } else { None };
+        let timeout = self.timeout.map(|spec| TimeoutConfig {
+            duration: Duration::from_millis(spec.timeout_ms),
+            // `enqueue` checks `abandoned >= max_abandoned`, so a 0 here would
+            // report ENQUEUE_BUSY on the very first call, before anything has
+            // ever been abandoned; clamp to a minimum of one so the cap only
+            // bites once an item has actually been abandoned.
+            max_abandoned: spec.max_abandoned.max(1),
+            on_timeout: spec.on_timeout,
+            abandoned: Arc::new(AtomicUsize::new(0)),
+        });
+        build_executor(
+            worker_queues,
+            sharded,
+            WorkerContext {
+                pending,
+                cancellation: Cancellation::new(),
+                listeners: Listeners::new(),
+                enqueue_barrier: EnqueueBarrier::new(),
+                callback,
+                retry: self.retry,
+                timeout,
+            },
+        )
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn sara_mix_executor_create(callback: MixCallback) -> *mut SaraMixExecutor {
+    SaraMixExecutorBuilder::new().build(callback)
+}
+
+/// Creates an executor backed by a bounded queue of `capacity` items. Once the
+/// queue is full, `sara_mix_executor_enqueue` drops the item and reports
+/// `ENQUEUE_FULL` instead of growing without bound.
+///
+/// When `coalesce` is true, an enqueue for a `(playlist_id, item_id)` pair
+/// that is already waiting in the queue is dropped (not re-queued) so rapid
+/// repeated requests for the same mix don't pile up.
 #[no_mangle]
-pub extern "C" fn sara_mix_executor_enqueue(handle: *mut SaraMixExecutor, playlist_id: *const c_char, item_id: *const c_char) {
+pub extern "C" fn sara_mix_executor_create_bounded(callback: MixCallback, capacity: usize, coalesce: bool) -> *mut SaraMixExecutor {
+    let mut builder = SaraMixExecutorBuilder::new();
+    builder.capacity = Some(capacity);
+    builder.coalesce = coalesce;
+    builder.build(callback)
+}
+
+/// Creates an unbounded executor with transient-failure retries: a callback
+/// returning non-zero is retried with exponential backoff (`base_delay_ms`,
+/// doubling each attempt, capped at `ceiling_ms`, up to `max_retries` times),
+/// each delay perturbed by up to ±25% jitter to avoid thundering-herd retries
+/// when many items fail at once.
+#[no_mangle]
+pub extern "C" fn sara_mix_executor_create_ex(callback: MixCallback, max_retries: u32, base_delay_ms: u64, ceiling_ms: u64) -> *mut SaraMixExecutor {
+    let mut builder = SaraMixExecutorBuilder::new();
+    builder.retry = RetryConfig {
+        max_retries,
+        base_delay: Duration::from_millis(base_delay_ms),
+        ceiling: Duration::from_millis(ceiling_ms),
+    };
+    builder.build(callback)
+}
+
+/// Creates an unbounded executor that runs each item's callback under a
+/// watchdog: if it doesn't return within `timeout_ms`, the worker abandons it
+/// (invoking `on_timeout(playlist_id, item_id)` if given) and moves on to the
+/// next queued item rather than blocking forever. The abandoned callback is
+/// not force-killed — it keeps running on its own thread until it returns.
+/// Once `max_abandoned` abandoned callbacks are still running,
+/// `sara_mix_executor_enqueue` returns `ENQUEUE_BUSY`; items queued before
+/// that point are still run and may be abandoned as well.
+/// `0` is treated as `1` (disallowing it outright would mean the very first
+/// enqueue, before anything has ever been abandoned, is already "busy").
+#[no_mangle]
+pub extern "C" fn sara_mix_executor_create_with_timeout(
+    callback: MixCallback,
+    on_timeout: TimeoutCallback,
+    timeout_ms: u64,
+    max_abandoned: usize,
+) -> *mut SaraMixExecutor {
+    let mut builder = SaraMixExecutorBuilder::new();
+    builder.timeout = Some(TimeoutSpec { timeout_ms, max_abandoned, on_timeout });
+    builder.build(callback)
+}
+
+/// Creates an executor with `threads` workers pulling from one queue, so
+/// independent mixes for different playlists run in parallel on multi-core
+/// machines. The callback is invoked concurrently from multiple threads and
+/// must be thread-safe.
+///
+/// When `serialize_per_playlist` is true, items are instead routed to one of
+/// `threads` queues by hashing `playlist_id`, so all items of a given
+/// playlist are always handled by the same worker and keep their relative
+/// order, while distinct playlists still run concurrently.
+#[no_mangle]
+pub extern "C" fn sara_mix_executor_create_pool(callback: MixCallback, threads: usize, serialize_per_playlist: bool) -> *mut SaraMixExecutor {
+    let mut builder = SaraMixExecutorBuilder::new();
+    builder.threads = threads;
+    builder.serialize_per_playlist = serialize_per_playlist;
+    builder.build(callback)
+}
+
+#[no_mangle]
+pub extern "C" fn sara_mix_executor_enqueue(handle: *mut SaraMixExecutor, playlist_id: *const c_char, item_id: *const c_char) -> c_int {
     if handle.is_null() || playlist_id.is_null() || item_id.is_null() {
-        return;
+        return ENQUEUE_DEAD;
     }
     let executor = unsafe { &*handle };
     if !executor.alive.load(Ordering::Relaxed) {
-        return;
+        return ENQUEUE_DEAD;
+    }
+    if let Some(timeout) = &executor.ctx.timeout {
+        if timeout.abandoned.load(Ordering::Relaxed) >= timeout.max_abandoned {
+            return ENQUEUE_BUSY;
+        }
     }
     let pl = unsafe { CStr::from_ptr(playlist_id) }.to_string_lossy().into_owned();
     let it = unsafe { CStr::from_ptr(item_id) }.to_string_lossy().into_owned();
-    let _ = executor.tx.send(Message::Work(pl, it));
+
+    // Only reserve and track a barrier sequence number when someone is
+    // actually subscribed to observe event ordering; otherwise every
+    // enqueue/dequeue would pay for `EnqueueBarrier`'s lock+condvar
+    // round-trip for no one, on every executor, including every shard of a
+    // sharded `sara_mix_executor_create_pool` pool.
+    let seq = if executor.ctx.listeners.has_subscribers() {
+        executor.ctx.enqueue_barrier.next_seq()
+    } else {
+        NO_BARRIER_SEQ
+    };
+    if let Some(pending) = &executor.ctx.pending {
+        let key = (pl.clone(), it.clone());
+        // Hold `pending_guard` across the push itself (not just the
+        // contains/insert), so "key is in `pending`" always means "really is
+        // queued and not yet dequeued": releasing the lock between insert and
+        // push would let a concurrent duplicate enqueue see the key, assume
+        // this push already succeeded, and return OK for an item that was in
+        // fact dropped as full.
+        let mut pending_guard = pending.lock().unwrap();
+        if pending_guard.contains(&key) {
+            return ENQUEUE_OK;
+        }
+        match executor.route_queue(&pl).try_push(Message::Work(pl.clone(), it.clone(), seq)) {
+            Ok(()) => {
+                pending_guard.insert(key);
+                drop(pending_guard);
+                executor.ctx.listeners.emit(EventKind::Enqueued, &pl, &it);
+                if seq != NO_BARRIER_SEQ {
+                    executor.ctx.enqueue_barrier.mark_announced(seq);
+                }
+                ENQUEUE_OK
+            }
+            Err(code) => code,
+        }
+    } else {
+        match executor.route_queue(&pl).try_push(Message::Work(pl.clone(), it.clone(), seq)) {
+            Ok(()) => {
+                executor.ctx.listeners.emit(EventKind::Enqueued, &pl, &it);
+                if seq != NO_BARRIER_SEQ {
+                    executor.ctx.enqueue_barrier.mark_announced(seq);
+                }
+                ENQUEUE_OK
+            }
+            Err(code) => code,
+        }
+    }
+}
+
+/// Cancels all not-yet-started work queued for `playlist_id` and arranges for
+/// an item from that playlist that is already running to be abandoned
+/// instead of retried, if its callback reports failure. A callback that is
+/// currently mid-flight and succeeds still runs to completion; only pending
+/// and retried work is affected.
+#[no_mangle]
+pub extern "C" fn sara_mix_executor_cancel(handle: *mut SaraMixExecutor, playlist_id: *const c_char) {
+    if handle.is_null() || playlist_id.is_null() {
+        return;
+    }
+    let executor = unsafe { &*handle };
+    let pl = unsafe { CStr::from_ptr(playlist_id) }.to_string_lossy().into_owned();
+
+    let removed = executor.route_queue(&pl).cancel_playlist(&pl);
+    if let Some(pending) = &executor.ctx.pending {
+        pending.lock().unwrap().retain(|(p, _)| p != &pl);
+    }
+    executor.ctx.cancellation.cancel_playlist(&pl);
+    for (playlist, item, seq) in &removed {
+        // This item will never be popped by a worker, so nothing would
+        // otherwise clear its enqueue_barrier entry. `seq` is `NO_BARRIER_SEQ`
+        // when no listener was subscribed at enqueue time, so there's no
+        // entry to clear.
+        if *seq != NO_BARRIER_SEQ {
+            executor.ctx.enqueue_barrier.forget(*seq);
+        }
+        executor.ctx.listeners.emit(EventKind::Cancelled, playlist, item);
+    }
+}
+
+/// Registers `listener` to receive `(event_kind, playlist_id, item_id)`
+/// lifecycle notifications (enqueued / started / completed / failed /
+/// cancelled). Returns an opaque, non-zero subscription id that
+/// `sara_mix_executor_unsubscribe` can later remove, or `0` if `handle` or
+/// `listener` is null.
+///
+/// Under a multi-threaded pool (`sara_mix_executor_create_pool`), `listener`
+/// can be invoked concurrently from multiple worker threads and must be
+/// thread-safe.
+#[no_mangle]
+pub extern "C" fn sara_mix_executor_subscribe(handle: *mut SaraMixExecutor, listener: Option<EventListener>) -> u64 {
+    let Some(listener) = listener else {
+        return 0;
+    };
+    if handle.is_null() {
+        return 0;
+    }
+    let executor = unsafe { &*handle };
+    executor.ctx.listeners.subscribe(listener)
+}
+
+/// Removes a listener previously registered with `sara_mix_executor_subscribe`.
+#[no_mangle]
+pub extern "C" fn sara_mix_executor_unsubscribe(handle: *mut SaraMixExecutor, subscription_id: u64) {
+    if handle.is_null() {
+        return;
+    }
+    let executor = unsafe { &*handle };
+    executor.ctx.listeners.unsubscribe(subscription_id);
 }
 
 #[no_mangle]
@@ -73,9 +903,446 @@ pub extern "C" fn sara_mix_executor_destroy(handle: *mut SaraMixExecutor) {
     }
     let mut executor = unsafe { Box::from_raw(handle) };
     executor.alive.store(false, Ordering::Relaxed);
-    let _ = executor.tx.send(Message::Shutdown);
-    if let Some(thread) = executor.thread.take() {
+    // One Shutdown per worker thread, routed through that worker's queue
+    // (the same shared queue, once per thread, in plain pool mode).
+    for queue in &executor.worker_queues {
+        queue.push_always(Message::Shutdown);
+    }
+    for thread in executor.threads.drain(..) {
         let _ = thread.join();
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn queue_try_push_respects_capacity() {
+        let queue = Queue::new(Some(1));
+        assert!(queue.try_push(Message::Work("p".to_string(), "a".to_string(), 1)).is_ok());
+        assert_eq!(queue.try_push(Message::Work("p".to_string(), "b".to_string(), 2)), Err(ENQUEUE_FULL));
+    }
+
+    #[test]
+    fn queue_pop_blocking_is_fifo() {
+        let queue = Queue::new(None);
+        queue.try_push(Message::Work("p".to_string(), "a".to_string(), 1)).unwrap();
+        queue.try_push(Message::Work("p".to_string(), "b".to_string(), 2)).unwrap();
+        match queue.pop_blocking() {
+            Message::Work(_, item, _) => assert_eq!(item, "a"),
+            Message::Shutdown => panic!("expected work"),
+        }
+        match queue.pop_blocking() {
+            Message::Work(_, item, _) => assert_eq!(item, "b"),
+            Message::Shutdown => panic!("expected work"),
+        }
+    }
+
+    #[test]
+    fn queue_cancel_playlist_removes_only_matching_not_yet_started_items() {
+        let queue = Queue::new(None);
+        queue.try_push(Message::Work("p1".to_string(), "a".to_string(), 1)).unwrap();
+        queue.try_push(Message::Work("p2".to_string(), "b".to_string(), 2)).unwrap();
+        queue.try_push(Message::Work("p1".to_string(), "c".to_string(), 3)).unwrap();
+
+        let removed = queue.cancel_playlist("p1");
+        assert_eq!(removed, vec![("p1".to_string(), "a".to_string(), 1), ("p1".to_string(), "c".to_string(), 3)]);
+        match queue.pop_blocking() {
+            Message::Work(pl, _, _) => assert_eq!(pl, "p2"),
+            Message::Shutdown => panic!("expected work"),
+        }
+    }
+
+    #[test]
+    fn retry_config_backoff_doubles_and_is_capped_by_ceiling() {
+        let retry = RetryConfig {
+            max_retries: 5,
+            base_delay: Duration::from_millis(100),
+            ceiling: Duration::from_millis(300),
+        };
+        assert_eq!(retry.delay_for(1), Duration::from_millis(100));
+        assert_eq!(retry.delay_for(2), Duration::from_millis(200));
+        assert_eq!(retry.delay_for(3), Duration::from_millis(300)); // would be 400 uncapped
+        assert_eq!(retry.delay_for(10), Duration::from_millis(300));
+    }
+
+    #[test]
+    fn retry_config_none_allows_no_retries() {
+        assert_eq!(RetryConfig::NONE.max_retries, 0);
+    }
+
+    thread_local! {
+        static EMIT_CALLS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe extern "C" fn record_emit(_kind: c_int, _playlist: *const c_char, _item: *const c_char) {
+        EMIT_CALLS.with(|calls| calls.set(calls.get() + 1));
+    }
+
+    #[test]
+    fn listeners_emit_fans_out_to_every_subscriber() {
+        let listeners = Listeners::new();
+        listeners.subscribe(record_emit);
+        listeners.subscribe(record_emit);
+        listeners.emit(EventKind::Started, "p", "i");
+        EMIT_CALLS.with(|calls| assert_eq!(calls.get(), 2));
+    }
+
+    #[test]
+    fn listeners_unsubscribe_stops_future_emits() {
+        let listeners = Listeners::new();
+        let id = listeners.subscribe(record_emit);
+        listeners.unsubscribe(id);
+        listeners.emit(EventKind::Started, "p", "i");
+        EMIT_CALLS.with(|calls| assert_eq!(calls.get(), 0));
+    }
+
+    #[test]
+    fn listeners_has_subscribers_tracks_subscribe_and_unsubscribe() {
+        let listeners = Listeners::new();
+        assert!(!listeners.has_subscribers());
+        let id = listeners.subscribe(record_emit);
+        assert!(listeners.has_subscribers());
+        listeners.unsubscribe(id);
+        assert!(!listeners.has_subscribers());
+    }
+
+    #[test]
+    fn cancellation_does_not_affect_a_later_unrelated_attempt() {
+        let cancellation = Cancellation::new();
+        let first = cancellation.begin("p");
+        cancellation.finish("p", first);
+        // Nothing is in flight for "p" anymore, so this must be a no-op.
+        cancellation.cancel_playlist("p");
+
+        let second = cancellation.begin("p");
+        assert!(!cancellation.is_cancelled(second));
+        cancellation.finish("p", second);
+    }
+
+    #[test]
+    fn cancellation_cancels_only_the_attempt_live_at_call_time() {
+        let cancellation = Cancellation::new();
+        let token = cancellation.begin("p");
+        cancellation.cancel_playlist("p");
+        assert!(cancellation.is_cancelled(token));
+        cancellation.finish("p", token);
+    }
+
+    #[test]
+    fn enqueue_barrier_forget_does_not_leak_regardless_of_race_order() {
+        let barrier = EnqueueBarrier::new();
+
+        // forget() arrives before the matching mark_announced(): the
+        // tombstone must be consumed by mark_announced rather than lingering.
+        let early_forget = barrier.next_seq();
+        barrier.forget(early_forget);
+        barrier.mark_announced(early_forget);
+
+        // mark_announced() arrives before the matching forget(): forget must
+        // claim (and clear) the existing announced entry instead of adding a
+        // tombstone nothing will ever consume.
+        let late_forget = barrier.next_seq();
+        barrier.mark_announced(late_forget);
+        barrier.forget(late_forget);
+
+        let state = barrier.state.lock().unwrap();
+        assert!(state.announced.is_empty(), "announced should not retain cancelled seqs");
+        assert!(state.forgotten.is_empty(), "forgotten tombstones should not outlive their match");
+    }
+
+    // The tests below drive the public C entry points end-to-end (through the
+    // real worker() loop, not just the helper types in isolation), matching
+    // what each constructor actually promises.
+
+    static BOUNDED_CALLS: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+    unsafe extern "C" fn bounded_cb(playlist: *const c_char, item: *const c_char) -> c_int {
+        let pl = unsafe { CStr::from_ptr(playlist) }.to_string_lossy().into_owned();
+        let it = unsafe { CStr::from_ptr(item) }.to_string_lossy().into_owned();
+        thread::sleep(Duration::from_millis(150));
+        BOUNDED_CALLS.lock().unwrap().push((pl, it));
+        0
+    }
+
+    #[test]
+    fn bounded_coalescing_enqueue_reports_full_and_drops_duplicates() {
+        let handle = sara_mix_executor_create_bounded(Some(bounded_cb), 1, true);
+        let pl = CString::new("p").unwrap();
+        let a = CString::new("a").unwrap();
+        let b = CString::new("b").unwrap();
+        let c = CString::new("c").unwrap();
+
+        // Dequeued immediately by the single worker; the queue is now empty.
+        assert_eq!(sara_mix_executor_enqueue(handle, pl.as_ptr(), a.as_ptr()), ENQUEUE_OK);
+        thread::sleep(Duration::from_millis(100));
+        // Fills the bounded (capacity 1) queue while "a" is still running.
+        assert_eq!(sara_mix_executor_enqueue(handle, pl.as_ptr(), b.as_ptr()), ENQUEUE_OK);
+        // Coalesced: "b" is already pending, so this must not double-queue it.
+        assert_eq!(sara_mix_executor_enqueue(handle, pl.as_ptr(), b.as_ptr()), ENQUEUE_OK);
+        // The queue is full ("b" hasn't started), so this is rejected.
+        assert_eq!(sara_mix_executor_enqueue(handle, pl.as_ptr(), c.as_ptr()), ENQUEUE_FULL);
+
+        thread::sleep(Duration::from_millis(500));
+        sara_mix_executor_destroy(handle);
+
+        let calls = BOUNDED_CALLS.lock().unwrap();
+        assert_eq!(calls.len(), 2, "expected exactly a then b, got {calls:?}");
+        assert_eq!(calls[0], ("p".to_string(), "a".to_string()));
+        assert_eq!(calls[1], ("p".to_string(), "b".to_string()));
+    }
+
+    static ORDER_EVENTS: Mutex<Vec<c_int>> = Mutex::new(Vec::new());
+
+    unsafe extern "C" fn noop_cb(_playlist: *const c_char, _item: *const c_char) -> c_int {
+        0
+    }
+
+    unsafe extern "C" fn record_order_listener(kind: c_int, _playlist: *const c_char, _item: *const c_char) {
+        ORDER_EVENTS.lock().unwrap().push(kind);
+    }
+
+    #[test]
+    fn enqueued_event_always_fires_before_started_for_the_same_item() {
+        let handle = sara_mix_executor_create(Some(noop_cb));
+        sara_mix_executor_subscribe(handle, Some(record_order_listener));
+        let pl = CString::new("p").unwrap();
+
+        for i in 0..200 {
+            let it = CString::new(format!("i{i}")).unwrap();
+            assert_eq!(sara_mix_executor_enqueue(handle, pl.as_ptr(), it.as_ptr()), ENQUEUE_OK);
+        }
+
+        thread::sleep(Duration::from_millis(200));
+        sara_mix_executor_destroy(handle);
+
+        let events = ORDER_EVENTS.lock().unwrap();
+        let mut enqueued_so_far = 0;
+        for &kind in events.iter() {
+            if kind == EventKind::Enqueued as c_int {
+                enqueued_so_far += 1;
+            } else if kind == EventKind::Started as c_int {
+                assert!(enqueued_so_far > 0, "Started fired with no matching Enqueued observed yet");
+                enqueued_so_far -= 1;
+            }
+        }
+    }
+
+    #[test]
+    fn no_callback_executor_still_clears_its_enqueue_barrier_entry() {
+        let handle = sara_mix_executor_create(None);
+        // A subscriber makes enqueue reserve a barrier sequence number, so
+        // there is an entry for the worker to clear.
+        sara_mix_executor_subscribe(handle, Some(record_emit));
+        let pl = CString::new("p").unwrap();
+        let it = CString::new("i").unwrap();
+        assert_eq!(sara_mix_executor_enqueue(handle, pl.as_ptr(), it.as_ptr()), ENQUEUE_OK);
+
+        thread::sleep(Duration::from_millis(50));
+        let executor = unsafe { &*handle };
+        let state = executor.ctx.enqueue_barrier.state.lock().unwrap();
+        assert!(state.announced.is_empty(), "a None callback must not leave its seq behind in `announced`");
+        drop(state);
+        sara_mix_executor_destroy(handle);
+    }
+
+    #[test]
+    fn enqueue_skips_the_barrier_entirely_with_no_subscribers() {
+        let handle = sara_mix_executor_create(Some(noop_cb));
+        let pl = CString::new("p").unwrap();
+        let it = CString::new("i").unwrap();
+        assert_eq!(sara_mix_executor_enqueue(handle, pl.as_ptr(), it.as_ptr()), ENQUEUE_OK);
+
+        thread::sleep(Duration::from_millis(50));
+        let executor = unsafe { &*handle };
+        // Nothing was ever subscribed, so the item should never have touched
+        // `EnqueueBarrier` at all: `next_seq` never advanced past its initial
+        // value and nothing was recorded as announced.
+        assert_eq!(executor.ctx.enqueue_barrier.next_seq.load(Ordering::Relaxed), 1);
+        let state = executor.ctx.enqueue_barrier.state.lock().unwrap();
+        assert!(state.announced.is_empty());
+        assert!(state.forgotten.is_empty());
+        drop(state);
+        sara_mix_executor_destroy(handle);
+    }
+
+    static RETRY_CALLS: AtomicUsize = AtomicUsize::new(0);
+    static RETRY_FAILED_EVENTS: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe extern "C" fn always_fails_cb(_playlist: *const c_char, _item: *const c_char) -> c_int {
+        RETRY_CALLS.fetch_add(1, Ordering::Relaxed);
+        1
+    }
+
+    unsafe extern "C" fn record_failed_listener(kind: c_int, _playlist: *const c_char, _item: *const c_char) {
+        if kind == EventKind::Failed as c_int {
+            RETRY_FAILED_EVENTS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn failing_callback_is_retried_with_backoff_then_reported_failed() {
+        let handle = sara_mix_executor_create_ex(Some(always_fails_cb), 2, 10, 50);
+        sara_mix_executor_subscribe(handle, Some(record_failed_listener));
+        let pl = CString::new("p").unwrap();
+        let it = CString::new("i").unwrap();
+        assert_eq!(sara_mix_executor_enqueue(handle, pl.as_ptr(), it.as_ptr()), ENQUEUE_OK);
+
+        thread::sleep(Duration::from_millis(300));
+        sara_mix_executor_destroy(handle);
+
+        // One initial attempt plus two retries.
+        assert_eq!(RETRY_CALLS.load(Ordering::Relaxed), 3);
+        assert_eq!(RETRY_FAILED_EVENTS.load(Ordering::Relaxed), 1);
+    }
+
+    static CANCEL_RAN: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    static CANCEL_EVENTS: Mutex<Vec<(c_int, String)>> = Mutex::new(Vec::new());
+
+    unsafe extern "C" fn cancel_track_cb(_playlist: *const c_char, item: *const c_char) -> c_int {
+        let it = unsafe { CStr::from_ptr(item) }.to_string_lossy().into_owned();
+        if it == "blocker" {
+            thread::sleep(Duration::from_millis(200));
+        }
+        CANCEL_RAN.lock().unwrap().push(it);
+        0
+    }
+
+    unsafe extern "C" fn cancel_event_listener(kind: c_int, _playlist: *const c_char, item: *const c_char) {
+        let it = unsafe { CStr::from_ptr(item) }.to_string_lossy().into_owned();
+        CANCEL_EVENTS.lock().unwrap().push((kind, it));
+    }
+
+    #[test]
+    fn cancel_removes_a_not_yet_started_item_before_its_callback_ever_runs() {
+        let handle = sara_mix_executor_create(Some(cancel_track_cb));
+        sara_mix_executor_subscribe(handle, Some(cancel_event_listener));
+        let pl = CString::new("p").unwrap();
+        let blocker = CString::new("blocker").unwrap();
+        let pending = CString::new("pending").unwrap();
+
+        // The single worker is busy with "blocker" for 200ms, so "pending"
+        // is still sitting in the queue (never started) when we cancel.
+        assert_eq!(sara_mix_executor_enqueue(handle, pl.as_ptr(), blocker.as_ptr()), ENQUEUE_OK);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(sara_mix_executor_enqueue(handle, pl.as_ptr(), pending.as_ptr()), ENQUEUE_OK);
+
+        sara_mix_executor_cancel(handle, pl.as_ptr());
+
+        thread::sleep(Duration::from_millis(300));
+        sara_mix_executor_destroy(handle);
+
+        let ran = CANCEL_RAN.lock().unwrap();
+        assert!(!ran.contains(&"pending".to_string()), "a cancelled item's callback must never run");
+
+        let events = CANCEL_EVENTS.lock().unwrap();
+        assert!(
+            events.contains(&(EventKind::Cancelled as c_int, "pending".to_string())),
+            "expected a Cancelled event for the removed item, got {events:?}"
+        );
+    }
+
+    static RETRY_CANCEL_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe extern "C" fn always_fails_retry_cancel_cb(_playlist: *const c_char, _item: *const c_char) -> c_int {
+        RETRY_CANCEL_CALLS.fetch_add(1, Ordering::Relaxed);
+        1
+    }
+
+    #[test]
+    fn cancel_skips_a_pending_retry_for_an_already_in_flight_item() {
+        let handle = sara_mix_executor_create_ex(Some(always_fails_retry_cancel_cb), 5, 100, 100);
+        let pl = CString::new("p2").unwrap();
+        let it = CString::new("retryme").unwrap();
+        assert_eq!(sara_mix_executor_enqueue(handle, pl.as_ptr(), it.as_ptr()), ENQUEUE_OK);
+
+        // Wait for the first attempt to actually fire before cancelling, rather
+        // than guessing a sleep duration: under load the worker might not have
+        // dequeued the item yet, which would let cancel() remove it before any
+        // attempt runs and make the assertion below flaky.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while RETRY_CANCEL_CALLS.load(Ordering::Relaxed) == 0 {
+            assert!(Instant::now() < deadline, "first attempt never fired");
+            thread::sleep(Duration::from_millis(5));
+        }
+        sara_mix_executor_cancel(handle, pl.as_ptr());
+
+        thread::sleep(Duration::from_millis(400));
+        sara_mix_executor_destroy(handle);
+
+        // Only the attempt already in flight when cancel() ran should have
+        // fired; every retry after it must be skipped.
+        assert_eq!(RETRY_CANCEL_CALLS.load(Ordering::Relaxed), 1);
+    }
+
+    unsafe extern "C" fn hangs_cb(_playlist: *const c_char, _item: *const c_char) -> c_int {
+        thread::sleep(Duration::from_secs(2));
+        0
+    }
+
+    static TIMEOUT_FIRED: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe extern "C" fn on_timeout_cb(_playlist: *const c_char, _item: *const c_char) {
+        TIMEOUT_FIRED.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn watchdog_abandons_hanging_callback_and_trips_busy() {
+        let handle = sara_mix_executor_create_with_timeout(Some(hangs_cb), Some(on_timeout_cb), 50, 1);
+        let pl = CString::new("p").unwrap();
+        let first = CString::new("first").unwrap();
+        let second = CString::new("second").unwrap();
+
+        assert_eq!(sara_mix_executor_enqueue(handle, pl.as_ptr(), first.as_ptr()), ENQUEUE_OK);
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(TIMEOUT_FIRED.load(Ordering::Relaxed), 1);
+        // The one allowed abandoned callback is still "running" (orphaned),
+        // so a second item must be rejected as busy.
+        assert_eq!(sara_mix_executor_enqueue(handle, pl.as_ptr(), second.as_ptr()), ENQUEUE_BUSY);
+
+        sara_mix_executor_destroy(handle);
+    }
+
+    #[test]
+    fn zero_max_abandoned_still_accepts_the_first_item() {
+        let handle = sara_mix_executor_create_with_timeout(Some(hangs_cb), Some(on_timeout_cb), 50, 0);
+        let pl = CString::new("p").unwrap();
+        let it = CString::new("only").unwrap();
+
+        assert_eq!(sara_mix_executor_enqueue(handle, pl.as_ptr(), it.as_ptr()), ENQUEUE_OK);
+
+        sara_mix_executor_destroy(handle);
+    }
+
+    static POOL_ORDER: Mutex<Vec<(thread::ThreadId, String)>> = Mutex::new(Vec::new());
+
+    unsafe extern "C" fn pool_record_cb(_playlist: *const c_char, item: *const c_char) -> c_int {
+        let it = unsafe { CStr::from_ptr(item) }.to_string_lossy().into_owned();
+        thread::sleep(Duration::from_millis(20));
+        POOL_ORDER.lock().unwrap().push((thread::current().id(), it));
+        0
+    }
+
+    #[test]
+    fn pool_serializes_one_playlist_onto_a_single_worker_in_order() {
+        let handle = sara_mix_executor_create_pool(Some(pool_record_cb), 4, true);
+        let pl = CString::new("p").unwrap();
+        for item in ["i0", "i1", "i2", "i3", "i4"] {
+            let it = CString::new(item).unwrap();
+            assert_eq!(sara_mix_executor_enqueue(handle, pl.as_ptr(), it.as_ptr()), ENQUEUE_OK);
+        }
+
+        thread::sleep(Duration::from_millis(500));
+        sara_mix_executor_destroy(handle);
+
+        let recorded = POOL_ORDER.lock().unwrap();
+        assert_eq!(recorded.len(), 5);
+        let first_thread = recorded[0].0;
+        for (idx, (thread_id, item)) in recorded.iter().enumerate() {
+            assert_eq!(*thread_id, first_thread, "playlist items ran on more than one worker");
+            assert_eq!(item, &format!("i{idx}"));
+        }
+    }
+}